@@ -0,0 +1,97 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Selectable crypto backend.
+//!
+//! Exactly one of the `crypto-rustcrypto`, `crypto-mbedtls` or
+//! `crypto-openssl` features must be enabled (`crypto-rustcrypto` is the
+//! `no_default_features`-friendly choice, since it's pure Rust and has no
+//! linked C library - the other two assume a host/OS build and are only
+//! meaningful together with the `os` feature). Everything above this module
+//! - in particular the `OpCode::InvokeRequest` handling in
+//! `interaction_model::engine` - talks to [`Sha256`] rather than to any
+//! backend crate directly, so swapping the feature flag is the only change
+//! needed to retarget the crypto implementation.
+
+#[cfg(not(any(
+    feature = "crypto-rustcrypto",
+    feature = "crypto-mbedtls",
+    feature = "crypto-openssl"
+)))]
+compile_error!(
+    "Exactly one of the `crypto-rustcrypto`, `crypto-mbedtls` or `crypto-openssl` features must be enabled"
+);
+
+#[cfg(all(feature = "crypto-rustcrypto", feature = "crypto-mbedtls"))]
+compile_error!("Only one crypto backend feature may be enabled at a time");
+#[cfg(all(feature = "crypto-rustcrypto", feature = "crypto-openssl"))]
+compile_error!("Only one crypto backend feature may be enabled at a time");
+#[cfg(all(feature = "crypto-mbedtls", feature = "crypto-openssl"))]
+compile_error!("Only one crypto backend feature may be enabled at a time");
+
+/// The output of a SHA-256 hash, backend-agnostic.
+pub const SHA256_HASH_LEN_BYTES: usize = 32;
+
+/// A one-shot SHA-256 hasher. Every backend below exposes the same
+/// allocation-free `digest` entry point so call sites don't need to know
+/// which one is linked in.
+pub trait Sha256 {
+    /// Hashes `data` in one shot, writing the 32-byte digest into `out`.
+    fn digest(data: &[u8], out: &mut [u8; SHA256_HASH_LEN_BYTES]);
+}
+
+#[cfg(feature = "crypto-rustcrypto")]
+pub struct RustCryptoSha256;
+
+#[cfg(feature = "crypto-rustcrypto")]
+impl Sha256 for RustCryptoSha256 {
+    fn digest(data: &[u8], out: &mut [u8; SHA256_HASH_LEN_BYTES]) {
+        use sha2::Digest;
+
+        let result = sha2::Sha256::digest(data);
+        out.copy_from_slice(&result);
+    }
+}
+
+#[cfg(feature = "crypto-mbedtls")]
+pub struct MbedTlsSha256;
+
+#[cfg(feature = "crypto-mbedtls")]
+impl Sha256 for MbedTlsSha256 {
+    fn digest(data: &[u8], out: &mut [u8; SHA256_HASH_LEN_BYTES]) {
+        mbedtls::hash::Md::hash(mbedtls::hash::Type::Sha256, data, out)
+            .expect("mbedtls sha256 hashing failed");
+    }
+}
+
+#[cfg(feature = "crypto-openssl")]
+pub struct OpenSslSha256;
+
+#[cfg(feature = "crypto-openssl")]
+impl Sha256 for OpenSslSha256 {
+    fn digest(data: &[u8], out: &mut [u8; SHA256_HASH_LEN_BYTES]) {
+        let digest = openssl::sha::sha256(data);
+        out.copy_from_slice(&digest);
+    }
+}
+
+#[cfg(feature = "crypto-rustcrypto")]
+pub type DefaultSha256 = RustCryptoSha256;
+#[cfg(feature = "crypto-mbedtls")]
+pub type DefaultSha256 = MbedTlsSha256;
+#[cfg(feature = "crypto-openssl")]
+pub type DefaultSha256 = OpenSslSha256;
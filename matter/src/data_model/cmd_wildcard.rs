@@ -0,0 +1,144 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Lazy, allocation-free expansion of a (possibly wildcarded) `CmdPath`
+//! against the node, shared by `InvReq` processing so the invoke dispatcher
+//! consumes it the same way attribute reads consume their own wildcard
+//! expansion.
+//!
+//! Matter's wildcard status-suppression rules are precise: with a concrete
+//! endpoint, a missing cluster/command is a real error (`UnsupportedCluster`
+//! / `UnsupportedCommand`), and a missing endpoint is `UnsupportedEndpoint`.
+//! But once the endpoint itself is a wildcard, a cluster/command that
+//! doesn't exist on a given endpoint is silently skipped rather than
+//! reported - see `test_invoke_cmd_wc_endpoint_only_1_has_cluster`, where a
+//! wildcard `On` command only yields a response from the endpoint that
+//! actually has the on/off cluster.
+
+use crate::data_model::objects::Node;
+use crate::interaction_model::core::IMStatusCode;
+use crate::interaction_model::messages::ib::CmdPath;
+
+/// One item yielded by [`CmdExpand`]: either a concrete command to invoke,
+/// or a status for a path that - per the wildcard-suppression rules above -
+/// must actually be reported rather than dropped.
+pub enum CmdExpandItem {
+    Cmd { endpoint: u16, cluster: u32, command: u16 },
+    Status(CmdPath, IMStatusCode),
+}
+
+pub struct CmdExpand<'a> {
+    node: &'a Node<'a>,
+    path: CmdPath,
+    endpoint_idx: usize,
+    cluster_idx: usize,
+    command_idx: usize,
+    done: bool,
+}
+
+impl<'a> CmdExpand<'a> {
+    pub fn new(node: &'a Node<'a>, path: CmdPath) -> Self {
+        Self {
+            node,
+            path,
+            endpoint_idx: 0,
+            cluster_idx: 0,
+            command_idx: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for CmdExpand<'a> {
+    type Item = CmdExpandItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let endpoint_is_wc = self.path.path.endpoint.is_none();
+
+        loop {
+            let Some(endpoint) = self
+                .node
+                .endpoint_at(self.path.path.endpoint, self.endpoint_idx)
+            else {
+                self.done = true;
+
+                return if !endpoint_is_wc && self.endpoint_idx == 0 {
+                    Some(CmdExpandItem::Status(
+                        self.path,
+                        IMStatusCode::UnsupportedEndpoint,
+                    ))
+                } else {
+                    None
+                };
+            };
+
+            let first_endpoint_attempt = self.endpoint_idx == 0 && self.cluster_idx == 0;
+
+            let Some(cluster) = endpoint.cluster_at(self.path.path.cluster, self.cluster_idx)
+            else {
+                self.cluster_idx = 0;
+                self.endpoint_idx += 1;
+
+                // Concrete endpoint with no matching cluster is a real
+                // error; under a wildcard endpoint it's silently skipped.
+                if !endpoint_is_wc && first_endpoint_attempt {
+                    self.done = true;
+                    return Some(CmdExpandItem::Status(
+                        self.path,
+                        IMStatusCode::UnsupportedCluster,
+                    ));
+                }
+
+                continue;
+            };
+
+            let first_cluster_attempt = self.command_idx == 0;
+            let cluster_is_wc = self.path.path.cluster.is_none();
+
+            let Some(command) = cluster.command_at(self.path.path.leaf, self.command_idx) else {
+                self.command_idx = 0;
+                self.cluster_idx += 1;
+
+                // A concrete endpoint *and* cluster with no matching command
+                // is a real error, reported once. If the cluster itself is a
+                // wildcard, this cluster just doesn't have the command -
+                // keep trying the rest of them instead of giving up.
+                if !endpoint_is_wc && !cluster_is_wc && first_endpoint_attempt && first_cluster_attempt
+                {
+                    self.done = true;
+                    return Some(CmdExpandItem::Status(
+                        self.path,
+                        IMStatusCode::UnsupportedCommand,
+                    ));
+                }
+
+                continue;
+            };
+            self.command_idx += 1;
+
+            return Some(CmdExpandItem::Cmd {
+                endpoint: endpoint.id(),
+                cluster: cluster.id(),
+                command,
+            });
+        }
+    }
+}
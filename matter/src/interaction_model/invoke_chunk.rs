@@ -0,0 +1,104 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Chunked `InvokeResponse` emission.
+//!
+//! `handle_commands` encodes every pending `InvResp` item into a
+//! single fixed `out_buf`, which silently truncates for large batches (e.g.
+//! a wildcard invoke fanning out to many endpoints, as
+//! `test_invoke_cmd_wc_endpoint_all_have_clusters` shows is routine). This
+//! drives `items` instead: write as many as fit in the current message, and
+//! when the next one wouldn't fit, stop and hand the item that didn't fit
+//! back to the caller via `lookahead` - so the next call, against a fresh
+//! `writer` for the next exchange message, resumes from exactly that item
+//! instead of re-expanding the command path from scratch.
+
+use crate::error::{Error, ErrorCode};
+use crate::interaction_model::messages::ib::InvResp;
+use crate::tlv::TLVWriter;
+
+/// One item queued for encoding into an `InvokeResponseMessage`: either
+/// command data or a command status, already resolved to a concrete path -
+/// i.e. what `CmdExpand` yields, turned into a response.
+pub struct PendingInvResp<'a> {
+    pub resp: InvResp<'a>,
+}
+
+/// Whether [`encode_chunk`] drained `items` completely, or stopped because
+/// the next item didn't fit - in which case the caller must send what was
+/// written so far as one `InvokeResponseMessage` and call `encode_chunk`
+/// again, against a fresh `writer`, to continue.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    Complete,
+    More,
+}
+
+/// Drives `items` into `writer` via `to_resp`, one `InvResp` at a time,
+/// stopping before the item that would overflow the buffer.
+///
+/// `lookahead` carries a resolved-but-not-yet-written item across calls: on
+/// entry, a `Some` left over from a prior call that returned
+/// [`ChunkOutcome::More`] is tried again before pulling anything new from
+/// `items`, and on a `More` return it is left populated with the item that
+/// didn't fit, ready for the next call.
+pub fn encode_chunk<'a, I, F>(
+    items: &mut I,
+    lookahead: &mut Option<PendingInvResp<'a>>,
+    mut to_resp: F,
+    writer: &mut TLVWriter,
+) -> Result<ChunkOutcome, Error>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> PendingInvResp<'a>,
+{
+    let mut encoded_any = false;
+
+    loop {
+        if lookahead.is_none() {
+            *lookahead = items.next().map(&mut to_resp);
+        }
+
+        let Some(pending) = lookahead.as_ref() else {
+            return Ok(ChunkOutcome::Complete);
+        };
+
+        let mark = writer.get_tail();
+
+        match pending.resp.to_tlv(writer, crate::tlv::TagType::Anonymous) {
+            Ok(()) => {
+                encoded_any = true;
+                *lookahead = None;
+            }
+            Err(e) if e.code() == ErrorCode::NoSpace => {
+                // Roll back the partial write; a single `InvResp` item must
+                // never be split across messages. `lookahead` keeps the item
+                // itself so the next call resumes here.
+                writer.rewind_to(mark);
+
+                if !encoded_any {
+                    // Not even one item fits in an otherwise-empty buffer:
+                    // nothing this function can do about that.
+                    return Err(ErrorCode::NoSpace.into());
+                }
+
+                return Ok(ChunkOutcome::More);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Timed interaction enforcement: the `TimedRequest` -> `InvokeRequest`
+//! handshake.
+//!
+//! `InvReq` already carries a `timed_request` flag, but until now nothing
+//! enforced the handshake it implies. A `TimedRequest` opens a window (its
+//! `timeout_ms` field, measured from receipt); the invoke that follows on
+//! the same exchange must land inside that window, and must say so via
+//! `timed_request: Some(true)`.
+
+use embassy_time::{Duration, Instant};
+
+use crate::interaction_model::core::IMStatusCode;
+
+/// Per-exchange state tracking a pending timed interaction. Lives alongside
+/// the rest of the exchange's transport state for the same reason MRP's
+/// retransmission state does - it only matters while this one exchange is
+/// in flight.
+#[derive(Debug, Clone, Copy)]
+pub struct TimedInteraction {
+    deadline: Instant,
+}
+
+impl TimedInteraction {
+    /// Record receipt of a `TimedRequest` with the given timeout.
+    pub fn open(timeout_ms: u16, now: Instant) -> Self {
+        Self {
+            deadline: now + Duration::from_millis(timeout_ms as u64),
+        }
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+}
+
+/// Checks an incoming `InvokeRequest` against this exchange's timed-request
+/// state (if any), per the Matter timed-interaction rules:
+///
+/// - a plain (non-timed) invoke with no pending `TimedRequest` proceeds
+///   normally;
+/// - `timed_request: Some(true)` with no pending `TimedRequest` on this
+///   exchange is rejected with `TimedRequestMismatch`;
+/// - a pending `TimedRequest` whose window has elapsed is rejected with
+///   `Timeout`, and the pending state is cleared either way once this call
+///   returns.
+pub fn check_invoke(
+    pending: &mut Option<TimedInteraction>,
+    timed_request: Option<bool>,
+    now: Instant,
+) -> Result<(), IMStatusCode> {
+    // The handshake is consumed either way: a stale `TimedRequest` must not
+    // leak into the next, unrelated invoke on this exchange.
+    let timed = pending.take();
+
+    match (timed, timed_request == Some(true)) {
+        (None, true) => Err(IMStatusCode::TimedRequestMismatch),
+        (None, false) => Ok(()),
+        (Some(_), false) => Err(IMStatusCode::TimedRequestMismatch),
+        (Some(timed), true) => {
+            if timed.expired(now) {
+                Err(IMStatusCode::Timeout)
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
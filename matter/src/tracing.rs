@@ -0,0 +1,40 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Tracing hook for the interaction-model dispatch path.
+//!
+//! The tests driving `handle_commands` (see `tests/data_model/commands.rs`)
+//! just call `env_logger::try_init()` and rely on the `log` crate being
+//! backed by a real logger. That's a fine default for a `std` host test, but
+//! `env_logger` itself pulls in `std` and isn't available to a microcontroller
+//! firmware driving [`interaction_model::engine::InvokeEngine`] from a
+//! `#![no_std]` build. The `os` feature draws the line: with it enabled, the
+//! crate is free to assume a host environment and `init()` wires up
+//! `env_logger`; without it, `init()` is a no-op and callers are expected to
+//! have already installed a `log::Log` implementation appropriate for their
+//! target (or none, in which case the `log` macros used throughout the
+//! dispatch path simply compile out).
+
+/// Installs a host-appropriate logger. On an embedded (`no_std`-compatible)
+/// build this is a no-op - the target is responsible for calling
+/// `log::set_logger` itself, if it wants log output at all.
+pub fn init() {
+    #[cfg(feature = "os")]
+    {
+        let _ = env_logger::try_init();
+    }
+}
@@ -15,6 +15,7 @@
  *    limitations under the License.
  */
 
+use embassy_time::{Duration, Instant};
 use matter::{
     data_model::{cluster_on_off, objects::EncodeValue},
     interaction_model::{
@@ -24,6 +25,7 @@ use matter::{
             ib::{CmdData, CmdPath, CmdStatus, InvResp},
             msg::InvReq,
         },
+        timed::{check_invoke, TimedInteraction},
     },
     tlv::{self, FromTLV, TLVArray},
 };
@@ -220,3 +222,35 @@ fn test_invoke_cmd_wc_endpoint_only_1_has_cluster() {
     ))];
     handle_commands(input, expected);
 }
+
+// `im_engine` doesn't drive the `TimedRequest` opcode, so these exercise the
+// `TimedRequest` -> `InvokeRequest` handshake directly against `check_invoke`
+// rather than through the full request/response pipeline the tests above use.
+
+#[test]
+fn test_timed_invoke_without_timed_request_is_rejected() {
+    let mut pending = None;
+    let result = check_invoke(&mut pending, Some(true), Instant::now());
+    assert_eq!(result, Err(IMStatusCode::TimedRequestMismatch));
+}
+
+#[test]
+fn test_timed_invoke_within_window_succeeds() {
+    let now = Instant::now();
+    let mut pending = Some(TimedInteraction::open(1000, now));
+
+    let result = check_invoke(&mut pending, Some(true), now + Duration::from_millis(500));
+
+    assert_eq!(result, Ok(()));
+    assert!(pending.is_none());
+}
+
+#[test]
+fn test_timed_invoke_after_window_times_out() {
+    let now = Instant::now();
+    let mut pending = Some(TimedInteraction::open(1000, now));
+
+    let result = check_invoke(&mut pending, Some(true), now + Duration::from_millis(1500));
+
+    assert_eq!(result, Err(IMStatusCode::Timeout));
+}
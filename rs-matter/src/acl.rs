@@ -0,0 +1,458 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Access Control List: the static, per-fabric grant list consulted on every
+//! attribute/command path, plus the accessor-side authorization decision
+//! built on top of it.
+
+use crate::data_model::objects::Privilege;
+use crate::error::{Error, ErrorCode};
+use crate::interaction_model::messages::GenericPath;
+
+pub const MAX_SUBJECTS: usize = 4;
+pub const MAX_TARGETS: usize = 3;
+/// Matter spec cap on the number of ACL entries a single fabric may install.
+pub const MAX_ACL_ENTRIES_PER_FABRIC: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    Case,
+    Group,
+    Pase,
+}
+
+/// Synthesizes a NOC CAT (CASE Authenticated Tag) subject id from an
+/// identifier and version, as carried in NOC subject lists:
+/// the low 16 bits are the version, the high 16 the CAT id.
+pub fn gen_noc_cat(id: u32, version: u16) -> u32 {
+    (id << 16) | version as u32
+}
+
+fn noc_cat_id(subject: u32) -> u32 {
+    subject >> 16
+}
+
+fn noc_cat_version(subject: u32) -> u16 {
+    (subject & 0xffff) as u16
+}
+
+/// `(endpoint, cluster, attribute)`, with `None` standing for a wildcard at
+/// that level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Target {
+    pub endpoint: Option<u16>,
+    pub cluster: Option<u32>,
+    pub attr: Option<u32>,
+}
+
+impl Target {
+    pub fn new(endpoint: Option<u16>, cluster: Option<u32>, attr: Option<u32>) -> Self {
+        Self {
+            endpoint,
+            cluster,
+            attr,
+        }
+    }
+
+    fn matches(&self, path: &GenericPath) -> bool {
+        self.endpoint.map_or(true, |e| Some(e) == path.endpoint)
+            && self.cluster.map_or(true, |c| Some(c) == path.cluster)
+            && self.attr.map_or(true, |a| Some(a) == path.leaf)
+    }
+
+    /// Whether `self` grants nothing that `other` doesn't already grant more
+    /// broadly - i.e. every field `self` pins down, `other` either pins to
+    /// the same value or leaves wildcarded.
+    fn subsumed_by(&self, other: &Target) -> bool {
+        field_subsumed(self.endpoint, other.endpoint)
+            && field_subsumed(self.cluster, other.cluster)
+            && field_subsumed(self.attr, other.attr)
+    }
+}
+
+fn field_subsumed<T: PartialEq>(narrower: Option<T>, broader: Option<T>) -> bool {
+    match broader {
+        None => true,
+        Some(b) => narrower.map_or(false, |n| n == b),
+    }
+}
+
+/// A single access control list entry: grants `privilege` over `targets` to
+/// `subjects` authenticated via `auth_mode`, on `fabric_idx`'s fabric.
+#[derive(Debug, Clone)]
+pub struct AclEntry {
+    pub fabric_idx: u8,
+    pub privilege: Privilege,
+    pub auth_mode: AuthMode,
+    pub subjects: heapless::Vec<u64, MAX_SUBJECTS>,
+    pub subject_cats: heapless::Vec<u32, MAX_SUBJECTS>,
+    pub targets: heapless::Vec<Target, MAX_TARGETS>,
+}
+
+impl AclEntry {
+    pub fn new(fabric_idx: u8, privilege: Privilege, auth_mode: AuthMode) -> Self {
+        Self {
+            fabric_idx,
+            privilege,
+            auth_mode,
+            subjects: heapless::Vec::new(),
+            subject_cats: heapless::Vec::new(),
+            targets: heapless::Vec::new(),
+        }
+    }
+
+    pub fn add_subject(&mut self, subject: u64) -> Result<(), Error> {
+        self.subjects.push(subject).map_err(|_| ErrorCode::NoSpace)?;
+        Ok(())
+    }
+
+    pub fn add_subject_catid(&mut self, cat: u32) -> Result<(), Error> {
+        self.subject_cats.push(cat).map_err(|_| ErrorCode::NoSpace)?;
+        Ok(())
+    }
+
+    /// Whether `self` and `other` would grant the exact same `(subject,
+    /// privilege)` pair, modulo their target lists - the merge key
+    /// [`AclMgr::canonicalize`] groups entries by.
+    fn same_grantee(&self, other: &Self) -> bool {
+        self.fabric_idx == other.fabric_idx
+            && self.auth_mode == other.auth_mode
+            && self.privilege == other.privilege
+            && self.subjects == other.subjects
+            && self.subject_cats == other.subject_cats
+    }
+
+    pub fn add_target(&mut self, target: Target) -> Result<(), Error> {
+        self.targets.push(target).map_err(|_| ErrorCode::NoSpace)?;
+        Ok(())
+    }
+
+    /// No subjects/CATs at all means "any subject on this fabric".
+    fn matches_subject(&self, node_id: u64, cat_ids: &[u32]) -> bool {
+        if self.subjects.is_empty() && self.subject_cats.is_empty() {
+            return true;
+        }
+
+        if self.subjects.iter().any(|s| *s == node_id) {
+            return true;
+        }
+
+        self.subject_cats.iter().any(|acl_cat| {
+            cat_ids.iter().any(|peer_cat| {
+                noc_cat_id(*peer_cat) == noc_cat_id(*acl_cat)
+                    // The peer's NOC may carry a CAT version that is equal to
+                    // or newer than the one the ACL was written against.
+                    && noc_cat_version(*peer_cat) >= noc_cat_version(*acl_cat)
+            })
+        })
+    }
+
+    /// No targets at all means "every target" (a universal grant).
+    fn matches_target(&self, path: &GenericPath) -> bool {
+        self.targets.is_empty() || self.targets.iter().any(|t| t.matches(path))
+    }
+}
+
+/// The accessing peer, as established by the secure session: which fabric
+/// it's on, how it authenticated, and its node id / CAT ids if CASE.
+pub struct Accessor<'a> {
+    pub fabric_idx: u8,
+    pub auth_mode: AuthMode,
+    pub node_id: u64,
+    pub cat_ids: &'a [u32],
+}
+
+/// A single authorization decision: "can `accessor` exercise `privilege`
+/// over `path`?", evaluated against an [`AclMgr`].
+pub struct AccessReq<'a> {
+    accessor: &'a Accessor<'a>,
+    path: GenericPath,
+    privilege: Privilege,
+}
+
+impl<'a> AccessReq<'a> {
+    pub fn new(accessor: &'a Accessor<'a>, path: &GenericPath, privilege: Privilege) -> Self {
+        Self {
+            accessor,
+            path: *path,
+            privilege,
+        }
+    }
+
+    pub fn allow(&self, acl_mgr: &mut AclMgr) -> bool {
+        acl_mgr.allow(self.accessor, &self.path, self.privilege)
+    }
+}
+
+/// The decision an [`AuthCallback`] makes for a path with no matching static
+/// ACL entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthDecision {
+    /// Allow this one request.
+    Grant,
+    /// Deny this one request.
+    Deny,
+    /// Allow it, and persist a new [`AclEntry`] so future requests from the
+    /// same accessor/target don't need to ask again.
+    GrantAndRemember,
+    /// Defer to the default, static-ACL-only behavior: wildcard paths are
+    /// silently dropped, exact paths return `UnsupportedAccess`.
+    Fallthrough,
+}
+
+/// Invoked once per exchange, the first time a path has no matching static
+/// ACL entry; its answer is then cached for the rest of the exchange so a
+/// single wildcard read doesn't re-invoke it once per expanded path.
+pub type AuthCallback = fn(&Accessor, &GenericPath, Privilege) -> AuthDecision;
+
+fn default_auth_callback(_accessor: &Accessor, _path: &GenericPath, _privilege: Privilege) -> AuthDecision {
+    AuthDecision::Fallthrough
+}
+
+/// Errors from validating or canonicalizing the ACL table - as opposed to
+/// [`Error`]/[`ErrorCode`], which cover the wire-level IM/secure-channel
+/// failure space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclError {
+    EmptySubjects,
+    PrivilegeTooLow,
+    TooManyTargets,
+    TooManyEntries,
+}
+
+pub struct AclMgr {
+    entries: heapless::Vec<AclEntry, { MAX_ACL_ENTRIES_PER_FABRIC * 8 }>,
+    auth_callback: AuthCallback,
+    /// Per-exchange cache of the last `AuthCallback` decision, so a wildcard
+    /// expansion invokes it at most once. Cleared by [`AclMgr::end_exchange`].
+    cached_decision: Option<AuthDecision>,
+}
+
+impl AclMgr {
+    pub const fn new() -> Self {
+        Self {
+            entries: heapless::Vec::new(),
+            auth_callback: default_auth_callback,
+            cached_decision: None,
+        }
+    }
+
+    pub fn add(&mut self, entry: AclEntry) -> Result<(), Error> {
+        self.entries.push(entry).map_err(|_| ErrorCode::NoSpace)?;
+        Ok(())
+    }
+
+    /// Like [`Self::add`], but validates the entry first and, on success,
+    /// immediately runs [`Self::canonicalize`] so the table never carries
+    /// persisted-but-unvalidated state.
+    pub fn add_canonical(&mut self, entry: AclEntry) -> Result<(), AclError> {
+        Self::validate(&entry)?;
+        self.add(entry).map_err(|_| AclError::TooManyEntries)?;
+        self.canonicalize()
+    }
+
+    fn validate(entry: &AclEntry) -> Result<(), AclError> {
+        if entry.subjects.is_empty() && entry.subject_cats.is_empty() {
+            return Err(AclError::EmptySubjects);
+        }
+
+        if (entry.privilege as u8) < (Privilege::VIEW as u8) {
+            return Err(AclError::PrivilegeTooLow);
+        }
+
+        if entry.targets.len() > MAX_TARGETS {
+            return Err(AclError::TooManyTargets);
+        }
+
+        Ok(())
+    }
+
+    /// Merges, de-duplicates and validates the ACL table in place:
+    ///
+    /// - entries with identical `(fabric, auth_mode, privilege, subjects)`
+    ///   are merged by unioning their target lists;
+    /// - a target fully covered by a broader wildcard target *in the same
+    ///   entry* is dropped;
+    /// - an entry whose every (now-canonical) target/subject pair is already
+    ///   granted by another entry at equal-or-higher privilege is dropped as
+    ///   redundant.
+    ///
+    /// After this call, no two entries grant exactly the same `(subject,
+    /// target)` pair, and `allow()` results are unchanged.
+    pub fn canonicalize(&mut self) -> Result<(), AclError> {
+        // Merge same-grantee entries by unioning their targets.
+        let mut merged: heapless::Vec<AclEntry, { MAX_ACL_ENTRIES_PER_FABRIC * 8 }> =
+            heapless::Vec::new();
+
+        for entry in self.entries.iter() {
+            if let Some(existing) = merged.iter_mut().find(|e| e.same_grantee(entry)) {
+                if existing.targets.is_empty() || entry.targets.is_empty() {
+                    // An empty target list is a universal grant, which
+                    // already covers every target the other entry lists.
+                    existing.targets.clear();
+                } else {
+                    for target in entry.targets.iter() {
+                        if !existing.targets.contains(target) {
+                            existing
+                                .add_target(*target)
+                                .map_err(|_| AclError::TooManyTargets)?;
+                        }
+                    }
+                }
+            } else {
+                merged.push(entry.clone()).map_err(|_| AclError::TooManyEntries)?;
+            }
+        }
+
+        // Drop targets subsumed by a broader wildcard target in the same
+        // entry.
+        for entry in merged.iter_mut() {
+            let kept: heapless::Vec<Target, MAX_TARGETS> = entry
+                .targets
+                .iter()
+                .filter(|t| {
+                    !entry
+                        .targets
+                        .iter()
+                        .any(|other| other != *t && t.subsumed_by(other))
+                })
+                .copied()
+                .collect();
+            entry.targets = kept;
+        }
+
+        // Drop entries whose grants are already fully implied by another,
+        // equal-or-higher-privilege entry.
+        let mut kept_entries: heapless::Vec<AclEntry, { MAX_ACL_ENTRIES_PER_FABRIC * 8 }> =
+            heapless::Vec::new();
+
+        for (idx, entry) in merged.iter().enumerate() {
+            let implied = merged.iter().enumerate().any(|(other_idx, other)| {
+                other_idx != idx
+                    && (other.privilege as u8) >= (entry.privilege as u8)
+                    && entry.fabric_idx == other.fabric_idx
+                    && entry.auth_mode == other.auth_mode
+                    && entry.subjects == other.subjects
+                    && entry.subject_cats == other.subject_cats
+                    // An empty target list is a universal grant: only
+                    // another universal grant could imply it.
+                    && !entry.targets.is_empty()
+                    && entry
+                        .targets
+                        .iter()
+                        .all(|t| other.targets.iter().any(|ot| t.subsumed_by(ot)))
+            });
+
+            if !implied {
+                kept_entries
+                    .push(entry.clone())
+                    .map_err(|_| AclError::TooManyEntries)?;
+            }
+        }
+
+        self.entries = kept_entries;
+
+        Ok(())
+    }
+
+    /// Registers the dynamic authorization hook consulted when no static
+    /// entry matches a request. Pass [`default_auth_callback`]'s behavior
+    /// (i.e. don't call this) to keep today's silent-drop semantics.
+    pub fn set_auth_callback(&mut self, callback: AuthCallback) {
+        self.auth_callback = callback;
+    }
+
+    /// Drop the cached dynamic-authorization decision; call this once an
+    /// exchange completes so the next one re-evaluates the callback.
+    pub fn end_exchange(&mut self) {
+        self.cached_decision = None;
+    }
+
+    /// The whole table, one [`AclEntry`] at a time. Each entry's `Display`
+    /// impl (see `acl_text`) renders it as one line - a greppable audit
+    /// format for operators, without needing to decode the TLV-encoded ACL
+    /// attribute.
+    pub fn dump(&self) -> impl Iterator<Item = &AclEntry> {
+        self.entries.iter()
+    }
+
+    pub fn allow(&mut self, accessor: &Accessor, path: &GenericPath, privilege: Privilege) -> bool {
+        let static_match = self.entries.iter().any(|e| {
+            e.fabric_idx == accessor.fabric_idx
+                && e.auth_mode == accessor.auth_mode
+                && (e.privilege as u8) >= (privilege as u8)
+                && e.matches_subject(accessor.node_id, accessor.cat_ids)
+                && e.matches_target(path)
+        });
+
+        if static_match {
+            return true;
+        }
+
+        // Cache the callback's answer for the rest of this exchange, so a
+        // wildcard read that expands to many paths invokes it once rather
+        // than once per expanded path - cleared by `end_exchange`.
+        let decision = *self
+            .cached_decision
+            .get_or_insert_with(|| (self.auth_callback)(accessor, path, privilege));
+
+        match decision {
+            AuthDecision::Grant => true,
+            AuthDecision::GrantAndRemember => {
+                self.remember(accessor, path, privilege);
+                true
+            }
+            AuthDecision::Deny => false,
+            AuthDecision::Fallthrough => {
+                // Preserve today's exact semantics: wildcard reads are
+                // silently dropped (handled by the caller skipping this
+                // path), exact reads surface `UnsupportedAccess` (also the
+                // caller's responsibility - `allow() == false` either way).
+                false
+            }
+        }
+    }
+
+    /// Synthesizes and persists an [`AclEntry`] granting `accessor` exactly
+    /// `privilege` over `path`, so future requests from the same accessor
+    /// against the same target don't need to ask the callback again. Best
+    /// effort: a full table or an out-of-range privilege silently skips
+    /// persisting (the one-shot grant from `allow` still goes through).
+    fn remember(&mut self, accessor: &Accessor, path: &GenericPath, privilege: Privilege) {
+        let mut entry = AclEntry::new(accessor.fabric_idx, privilege, accessor.auth_mode);
+
+        if entry.add_subject(accessor.node_id).is_err() {
+            return;
+        }
+
+        // A wildcard level in `path` stays a wildcard level in the target.
+        if entry
+            .add_target(Target::new(path.endpoint, path.cluster, path.leaf))
+            .is_err()
+        {
+            return;
+        }
+
+        let _ = self.add_canonical(entry);
+    }
+}
+
+impl Default for AclMgr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
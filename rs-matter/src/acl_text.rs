@@ -0,0 +1,251 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A compact, greppable textual encoding for [`AclEntry`]/[`Target`], in the
+//! spirit of PostgreSQL's `aclitem` grammar:
+//!
+//! ```text
+//! 1/case:0x6001,CAT:0xabcd.v2/(0,*,*),(*,6,*)=A
+//! ```
+//!
+//! `<fabric>/<auth_mode>:<subjects>/<targets>=<privilege>`, where a target
+//! triple is `(endpoint,cluster,attribute)` with `*` for a wildcard level,
+//! and privilege is one letter: `V`iew, `O`perate, `M`anage, `A`dminister.
+//! This is for diagnostics only - it is not used on the wire.
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::acl::{gen_noc_cat, AclEntry, AuthMode, Target};
+use crate::data_model::objects::Privilege;
+
+impl fmt::Display for AuthMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuthMode::Case => "case",
+            AuthMode::Group => "group",
+            AuthMode::Pase => "pase",
+        })
+    }
+}
+
+impl FromStr for AuthMode {
+    type Err = AclTextError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "case" => Ok(AuthMode::Case),
+            "group" => Ok(AuthMode::Group),
+            "pase" => Ok(AuthMode::Pase),
+            _ => Err(AclTextError::Malformed),
+        }
+    }
+}
+
+fn privilege_char(privilege: Privilege) -> char {
+    match privilege {
+        Privilege::VIEW => 'V',
+        Privilege::OPERATE => 'O',
+        Privilege::MANAGE => 'M',
+        Privilege::ADMIN => 'A',
+        _ => '?',
+    }
+}
+
+fn privilege_from_char(c: char) -> Result<Privilege, AclTextError> {
+    match c {
+        'V' => Ok(Privilege::VIEW),
+        'O' => Ok(Privilege::OPERATE),
+        'M' => Ok(Privilege::MANAGE),
+        'A' => Ok(Privilege::ADMIN),
+        _ => Err(AclTextError::Malformed),
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        write_wc(f, self.endpoint)?;
+        write!(f, ",")?;
+        write_wc(f, self.cluster)?;
+        write!(f, ",")?;
+        write_wc(f, self.attr)?;
+        write!(f, ")")
+    }
+}
+
+fn write_wc<T: fmt::Display>(f: &mut fmt::Formatter<'_>, v: Option<T>) -> fmt::Result {
+    match v {
+        Some(v) => write!(f, "{}", v),
+        None => write!(f, "*"),
+    }
+}
+
+fn parse_wc<T: FromStr>(s: &str) -> Result<Option<T>, AclTextError> {
+    if s == "*" {
+        Ok(None)
+    } else {
+        s.parse().map(Some).map_err(|_| AclTextError::Malformed)
+    }
+}
+
+impl FromStr for Target {
+    type Err = AclTextError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or(AclTextError::Malformed)?;
+
+        let mut parts = s.splitn(3, ',');
+        let endpoint = parse_wc(parts.next().ok_or(AclTextError::Malformed)?)?;
+        let cluster = parse_wc(parts.next().ok_or(AclTextError::Malformed)?)?;
+        let attr = parse_wc(parts.next().ok_or(AclTextError::Malformed)?)?;
+
+        Ok(Target::new(endpoint, cluster, attr))
+    }
+}
+
+impl fmt::Display for AclEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}:", self.fabric_idx, self.auth_mode)?;
+
+        let mut first = true;
+        for subject in self.subjects.iter() {
+            if !first {
+                write!(f, ",")?;
+            }
+            write!(f, "{:#x}", subject)?;
+            first = false;
+        }
+        for cat in self.subject_cats.iter() {
+            if !first {
+                write!(f, ",")?;
+            }
+            write!(f, "CAT:{:#x}.v{}", cat >> 16, cat & 0xffff)?;
+            first = false;
+        }
+
+        write!(f, "/")?;
+        for (idx, target) in self.targets.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", target)?;
+        }
+
+        write!(f, "={}", privilege_char(self.privilege))
+    }
+}
+
+/// Splits `"(a,b,c),(d,e,f)"` into `["(a,b,c)", "(d,e,f)"]`. A manual scan
+/// rather than `str::split(',')`, since each target's own commas must stay
+/// intact.
+fn split_targets(s: &str) -> heapless::Vec<&str, { crate::acl::MAX_TARGETS }> {
+    let mut out = heapless::Vec::new();
+    let mut start = None;
+
+    for (idx, c) in s.char_indices() {
+        match c {
+            '(' => start = Some(idx),
+            ')' => {
+                if let Some(begin) = start.take() {
+                    let _ = out.push(&s[begin..=idx]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclTextError {
+    Malformed,
+    TooManySubjects,
+    TooManyTargets,
+}
+
+impl FromStr for AclEntry {
+    type Err = AclTextError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (head, privilege) = s.rsplit_once('=').ok_or(AclTextError::Malformed)?;
+        let privilege = privilege_from_char(
+            privilege
+                .chars()
+                .next()
+                .ok_or(AclTextError::Malformed)?,
+        )?;
+
+        let mut head_parts = head.splitn(2, '/');
+        let fabric_and_auth = head_parts.next().ok_or(AclTextError::Malformed)?;
+        let rest = head_parts.next().ok_or(AclTextError::Malformed)?;
+
+        let mut rest_parts = rest.splitn(2, '/');
+        let subjects_str = rest_parts.next().ok_or(AclTextError::Malformed)?;
+        let targets_str = rest_parts.next().unwrap_or("");
+
+        let mut fa_parts = fabric_and_auth.splitn(2, '/');
+        let fabric_idx: u8 = fa_parts
+            .next()
+            .ok_or(AclTextError::Malformed)?
+            .parse()
+            .map_err(|_| AclTextError::Malformed)?;
+
+        let auth_mode_str = subjects_str
+            .split_once(':')
+            .map(|(mode, _)| mode)
+            .unwrap_or(subjects_str);
+        let auth_mode: AuthMode = auth_mode_str.parse()?;
+
+        let mut entry = AclEntry::new(fabric_idx, privilege, auth_mode);
+
+        if let Some((_, subjects)) = subjects_str.split_once(':') {
+            for subject in subjects.split(',').filter(|s| !s.is_empty()) {
+                if let Some(cat_str) = subject.strip_prefix("CAT:") {
+                    let (id_str, ver_str) = cat_str.split_once(".v").ok_or(AclTextError::Malformed)?;
+                    let id = u32::from_str_radix(id_str.trim_start_matches("0x"), 16)
+                        .map_err(|_| AclTextError::Malformed)?;
+                    let version: u16 = ver_str.parse().map_err(|_| AclTextError::Malformed)?;
+                    entry
+                        .add_subject_catid(gen_noc_cat(id, version))
+                        .map_err(|_| AclTextError::TooManySubjects)?;
+                } else {
+                    let node_id = u64::from_str_radix(subject.trim_start_matches("0x"), 16)
+                        .map_err(|_| AclTextError::Malformed)?;
+                    entry
+                        .add_subject(node_id)
+                        .map_err(|_| AclTextError::TooManySubjects)?;
+                }
+            }
+        }
+
+        // Targets are `(a,b,c)` triples separated by `,` - `split_targets`
+        // scans for the parentheses, keeping each triple's own internal
+        // commas intact.
+        for target in split_targets(targets_str) {
+            entry
+                .add_target(target.parse().map_err(|_| AclTextError::Malformed)?)
+                .map_err(|_| AclTextError::TooManyTargets)?;
+        }
+
+        Ok(entry)
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Lazy, ACL-filtered expansion of a (possibly wildcarded) attribute path
+//! against the data model.
+//!
+//! `ImEngine`'s test helpers drive `handle_read_reqs`/`handle_write_reqs`,
+//! which internally expand a `GenericPath`'s wildcard endpoint/cluster/
+//! attribute levels against the node and drop anything the accessor's ACLs
+//! don't permit. [`AttrExpand`] promotes that expansion to a public,
+//! allocation-free iterator so other transports and report generators can
+//! reuse it directly instead of re-implementing wildcard semantics.
+
+use crate::acl::{AccessReq, Accessor, AclMgr};
+use crate::data_model::objects::{AttrDetails, Node, Privilege};
+use crate::error::Error;
+use crate::interaction_model::core::IMStatusCode;
+use crate::interaction_model::messages::GenericPath;
+
+/// One item yielded by [`AttrExpand`]: either a concrete, ACL-permitted
+/// attribute, or an error status for a non-wildcard path that doesn't exist
+/// or isn't permitted.
+pub enum AttrExpandItem {
+    Attr(AttrDetails),
+    Status(GenericPath, IMStatusCode),
+}
+
+/// Lazily expands a (possibly wildcarded) [`GenericPath`] into concrete
+/// `(endpoint, cluster, attribute)` triples, filtering each by the
+/// accessor's ACLs as it goes.
+///
+/// The three wildcard levels are walked independently and nothing is
+/// allocated up front - important on `no_std` targets where the full
+/// expansion of a fully wildcarded path could be unbounded.
+pub struct AttrExpand<'a> {
+    node: &'a Node<'a>,
+    accessor: &'a Accessor<'a>,
+    acl_mgr: &'a mut AclMgr,
+    path: GenericPath,
+    privilege: Privilege,
+    // Cursor into the node: which endpoint/cluster/attribute we're about to
+    // try next. `None` in the path means "iterate all"; each cursor advances
+    // past every match, whether its path component is a wildcard or not.
+    endpoint_idx: usize,
+    cluster_idx: usize,
+    attr_idx: usize,
+    done: bool,
+}
+
+impl<'a> AttrExpand<'a> {
+    pub fn new(
+        node: &'a Node<'a>,
+        accessor: &'a Accessor<'a>,
+        acl_mgr: &'a mut AclMgr,
+        path: GenericPath,
+        privilege: Privilege,
+    ) -> Self {
+        Self {
+            node,
+            accessor,
+            acl_mgr,
+            path,
+            privilege,
+            endpoint_idx: 0,
+            cluster_idx: 0,
+            attr_idx: 0,
+            done: false,
+        }
+    }
+
+    /// Whether any cluster this expansion covers - from its start, not just
+    /// what is left to iterate - is backed by a handler that may need to
+    /// await (e.g. a cluster with an async read hook), without actually
+    /// materializing any attribute. Lets a caller decide up-front whether it
+    /// needs the async path at all.
+    pub fn may_await(&self) -> bool {
+        self.node
+            .endpoints_matching(self.path.endpoint)
+            .any(|ep| ep.clusters_matching(self.path.cluster).any(|c| c.is_async()))
+    }
+
+    fn is_acl_permitted(&mut self, concrete: &GenericPath) -> bool {
+        let req = AccessReq::new(self.accessor, concrete, self.privilege);
+        req.allow(self.acl_mgr)
+    }
+}
+
+impl<'a> Iterator for AttrExpand<'a> {
+    type Item = AttrExpandItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let Some(endpoint) = self.node.endpoint_at(self.path.endpoint, self.endpoint_idx)
+            else {
+                self.done = true;
+                // A concrete endpoint that doesn't exist is an error.
+                return if self.path.endpoint.is_some() && self.endpoint_idx == 0 {
+                    Some(AttrExpandItem::Status(self.path, IMStatusCode::UnsupportedEndpoint))
+                } else {
+                    None
+                };
+            };
+
+            let Some(cluster) = endpoint.cluster_at(self.path.cluster, self.cluster_idx) else {
+                let first_cluster_attempt = self.cluster_idx == 0;
+                self.cluster_idx = 0;
+                if self.path.endpoint.is_some() {
+                    self.done = true;
+                    // A concrete cluster missing from a concrete endpoint is
+                    // an error, reported once.
+                    return if self.path.cluster.is_some() && first_cluster_attempt {
+                        Some(AttrExpandItem::Status(self.path, IMStatusCode::UnsupportedCluster))
+                    } else {
+                        None
+                    };
+                }
+                self.endpoint_idx += 1;
+                continue;
+            };
+
+            let Some(attr) = cluster.attr_at(self.path.attr, self.attr_idx) else {
+                let first_attr_attempt = self.attr_idx == 0;
+                self.attr_idx = 0;
+                if self.path.cluster.is_some() {
+                    // Concrete cluster with no (more) matching attribute: a
+                    // wildcard endpoint silently skips it, an exact one is
+                    // an error - but only once, on the very first attempt.
+                    if self.path.endpoint.is_some() && first_attr_attempt {
+                        self.done = true;
+                        return Some(AttrExpandItem::Status(
+                            GenericPath::new(
+                                Some(endpoint.id()),
+                                Some(cluster.id()),
+                                self.path.attr,
+                            ),
+                            IMStatusCode::UnsupportedAttribute,
+                        ));
+                    }
+                    self.cluster_idx += 1;
+                    continue;
+                }
+                self.cluster_idx += 1;
+                continue;
+            };
+            self.attr_idx += 1;
+
+            let concrete = GenericPath::new(Some(endpoint.id()), Some(cluster.id()), Some(attr));
+
+            if self.is_acl_permitted(&concrete) {
+                return Some(AttrExpandItem::Attr(AttrDetails {
+                    node: self.node,
+                    endpoint: endpoint.id(),
+                    cluster: cluster.id(),
+                    attr_id: attr,
+                }));
+            } else if self.path.endpoint.is_some()
+                && self.path.cluster.is_some()
+                && self.path.attr.is_some()
+            {
+                // Fully concrete path with no ACL match: report it, don't
+                // silently drop it.
+                return Some(AttrExpandItem::Status(
+                    concrete,
+                    IMStatusCode::UnsupportedAccess,
+                ));
+            }
+            // Wildcard path with no ACL match: silently skip and keep going.
+        }
+    }
+}
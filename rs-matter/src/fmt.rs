@@ -0,0 +1,75 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A logging facade that forwards to either the `log` crate or `defmt`,
+//! picked by Cargo feature, mirroring the approach embassy uses so the same
+//! call sites work whether the crate runs on `std`/`embassy-net` or on an
+//! MCU that only has `defmt`-over-RTT.
+
+#[cfg(all(feature = "log", feature = "defmt"))]
+compile_error!("Only one of the `log` and `defmt` features may be enabled at a time");
+
+macro_rules! log_macro {
+    ($level:ident, $log_name:ident, $defmt_name:ident) => {
+        #[allow(unused_macros)]
+        macro_rules! $level {
+            ($($arg:tt)*) => {
+                {
+                    #[cfg(feature = "log")]
+                    ::log::$log_name!($($arg)*);
+                    #[cfg(feature = "defmt")]
+                    ::defmt::$defmt_name!($($arg)*);
+                    #[cfg(not(any(feature = "log", feature = "defmt")))]
+                    let _ = ($($arg)*,);
+                }
+            };
+        }
+    };
+}
+
+log_macro!(trace, trace, trace);
+log_macro!(debug, debug, debug);
+log_macro!(info, info, info);
+log_macro!(warn, warn, warn);
+log_macro!(error, error, error);
+
+pub(crate) use debug;
+pub(crate) use error;
+pub(crate) use info;
+pub(crate) use trace;
+pub(crate) use warn;
+
+/// Wraps a `core::fmt::Debug` value for use with the `{:?}` placeholder in
+/// the macros above, under either backend.
+///
+/// `defmt`'s `{:?}` placeholder requires `defmt::Format`, not
+/// `core::fmt::Debug` - so a call site that forwards a plain
+/// `#[derive(Debug)]` value (as every type logged this way in this crate
+/// is) compiles fine under `log` but fails to build at all under
+/// `defmt`. `defmt::Debug2Format` bridges exactly this gap; under `log`,
+/// which already wants `core::fmt::Debug`, this is a no-op passthrough.
+#[cfg(feature = "defmt")]
+#[allow(unused)]
+pub(crate) fn dbg<T: core::fmt::Debug>(value: &T) -> defmt::Debug2Format<'_, T> {
+    defmt::Debug2Format(value)
+}
+
+#[cfg(not(feature = "defmt"))]
+#[allow(unused)]
+pub(crate) fn dbg<T: core::fmt::Debug>(value: &T) -> &T {
+    value
+}
@@ -0,0 +1,258 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Subscribe-report support, built on the same per-cluster `data_ver`
+//! filtering that one-shot reads already apply via `DataVersionFilter`.
+//!
+//! A subscription retains the last-reported `data_ver` for every cluster its
+//! (possibly wildcard) attribute paths touch. On each reporting cycle, those
+//! paths are re-expanded and a cluster is only included in the report if its
+//! `data_ver` has advanced since the last cycle - an empty report (a
+//! heartbeat) is sent when nothing changed, so the controller can tell the
+//! subscription is still alive.
+
+use embassy_time::{Duration, Instant};
+
+use crate::acl::{AclMgr, Accessor};
+use crate::data_model::objects::{Node, Privilege};
+use crate::data_model::wildcard::{AttrExpand, AttrExpandItem};
+use crate::error::{Error, ErrorCode};
+use crate::interaction_model::messages::ib::{AttrPath, ClusterPath};
+
+/// Matter's "infinite" max-interval sentinel isn't modeled here; callers
+/// pick a concrete cap appropriate to their transport.
+pub const MAX_SUBSCRIPTIONS: usize = 3;
+const MAX_PATHS_PER_SUB: usize = 9;
+/// Bounds the per-subscription retained `data_ver` table so state stays
+/// `no_std`/embedded friendly instead of growing with the node's size.
+const MAX_TRACKED_CLUSTERS: usize = 8;
+
+pub type SubscriptionId = u32;
+
+struct TrackedCluster {
+    path: ClusterPath,
+    data_ver: u32,
+}
+
+pub struct Subscription {
+    pub id: SubscriptionId,
+    pub peer_node_id: u64,
+    paths: heapless::Vec<AttrPath, MAX_PATHS_PER_SUB>,
+    min_interval: Duration,
+    max_interval: Duration,
+    last_report: Instant,
+    tracked: heapless::Vec<TrackedCluster, MAX_TRACKED_CLUSTERS>,
+}
+
+/// One cluster's worth of changed attributes, as produced by
+/// [`SubscriptionMgr::report`].
+pub struct ClusterReport<'a> {
+    pub path: ClusterPath,
+    pub changed: heapless::Vec<AttrExpandItem, MAX_PATHS_PER_SUB>,
+    _node: core::marker::PhantomData<&'a ()>,
+}
+
+impl Subscription {
+    pub fn new(
+        id: SubscriptionId,
+        peer_node_id: u64,
+        paths: &[AttrPath],
+        min_interval: Duration,
+        max_interval: Duration,
+    ) -> Result<Self, Error> {
+        let mut owned = heapless::Vec::new();
+        for path in paths {
+            owned.push(*path).map_err(|_| ErrorCode::NoSpace)?;
+        }
+
+        Ok(Self {
+            id,
+            peer_node_id,
+            paths: owned,
+            min_interval,
+            max_interval,
+            last_report: Instant::now(),
+            tracked: heapless::Vec::new(),
+        })
+    }
+
+    fn due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_report) >= self.min_interval
+    }
+
+    fn overdue_for_heartbeat(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_report) >= self.max_interval
+    }
+
+    fn retained_ver(&self, path: &ClusterPath) -> Option<u32> {
+        self.tracked
+            .iter()
+            .find(|t| t.path == *path)
+            .map(|t| t.data_ver)
+    }
+
+    fn set_retained_ver(&mut self, path: ClusterPath, data_ver: u32) {
+        if let Some(tracked) = self.tracked.iter_mut().find(|t| t.path == path) {
+            tracked.data_ver = data_ver;
+        } else {
+            // Bounded by `MAX_TRACKED_CLUSTERS`: once the table is full, a
+            // cluster seen for the first time isn't tracked at all, so it is
+            // never change-filtered and gets re-reported every cycle - a
+            // correctness-preserving degradation.
+            let _ = self.tracked.push(TrackedCluster { path, data_ver });
+        }
+    }
+}
+
+pub struct SubscriptionMgr {
+    subscriptions: heapless::Vec<Subscription, MAX_SUBSCRIPTIONS>,
+}
+
+impl SubscriptionMgr {
+    pub const fn new() -> Self {
+        Self {
+            subscriptions: heapless::Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, subscription: Subscription) -> Result<(), Error> {
+        self.subscriptions
+            .push(subscription)
+            .map_err(|_| ErrorCode::NoSpace)?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: SubscriptionId) {
+        if let Some(idx) = self.subscriptions.iter().position(|s| s.id == id) {
+            self.subscriptions.swap_remove(idx);
+        }
+    }
+
+    /// Which subscriptions are due a reporting pass right now (honoring
+    /// `min_interval`), so a caller's event loop knows when to next poll
+    /// `report`.
+    pub fn due_subscriptions(&self, now: Instant) -> impl Iterator<Item = SubscriptionId> + '_ {
+        self.subscriptions
+            .iter()
+            .filter(move |s| s.due(now))
+            .map(|s| s.id)
+    }
+
+    /// Re-expands `subscription_id`'s attribute paths against `node`,
+    /// returning only the clusters whose `data_ver` advanced since the last
+    /// report (or, if the max-interval heartbeat is due and nothing
+    /// changed, an empty list - the caller sends an empty report either
+    /// way to keep the subscription alive).
+    pub fn report<'a>(
+        &mut self,
+        subscription_id: SubscriptionId,
+        node: &'a Node<'a>,
+        accessor: &'a Accessor<'a>,
+        acl_mgr: &mut AclMgr,
+    ) -> Result<heapless::Vec<ClusterReport<'a>, MAX_PATHS_PER_SUB>, Error> {
+        let now = Instant::now();
+
+        let sub = self
+            .subscriptions
+            .iter_mut()
+            .find(|s| s.id == subscription_id)
+            .ok_or(ErrorCode::NoExchange)?;
+
+        if !sub.due(now) {
+            // Too soon since the last report: `due_subscriptions` is what
+            // callers are expected to poll before calling `report` at all,
+            // but re-check here so a caller that ignores it can't drive a
+            // subscription faster than its `min_interval` promises.
+            return Ok(heapless::Vec::new());
+        }
+
+        let mut reports: heapless::Vec<ClusterReport<'a>, MAX_PATHS_PER_SUB> = heapless::Vec::new();
+        // `data_ver`s newly observed this pass, applied to `sub.tracked` only
+        // once every attribute of every path has been walked - advancing a
+        // cluster's retained version as soon as its *first* changed
+        // attribute is seen would make every attribute after it in the same
+        // cluster look unchanged and get dropped from this very report.
+        let mut observed: heapless::Vec<(ClusterPath, u32), MAX_PATHS_PER_SUB> = heapless::Vec::new();
+
+        for attr_path in sub.paths.iter() {
+            let expand = AttrExpand::new(
+                node,
+                accessor,
+                &mut *acl_mgr,
+                attr_path.to_gp(),
+                Privilege::VIEW,
+            );
+
+            for item in expand {
+                let AttrExpandItem::Attr(attr) = item else {
+                    continue;
+                };
+
+                let cluster_path = ClusterPath {
+                    node: None,
+                    endpoint: attr.endpoint,
+                    cluster: attr.cluster,
+                };
+
+                let current_ver = node.cluster_data_ver(attr.endpoint, attr.cluster);
+
+                if observed.iter().find(|(p, _)| *p == cluster_path).is_none() {
+                    let _ = observed.push((cluster_path, current_ver));
+                }
+
+                let changed = sub.retained_ver(&cluster_path) != Some(current_ver);
+
+                if changed {
+                    match reports.iter_mut().find(|r| r.path == cluster_path) {
+                        Some(report) => {
+                            let _ = report.changed.push(AttrExpandItem::Attr(attr));
+                        }
+                        None => {
+                            let mut changed_vec = heapless::Vec::new();
+                            let _ = changed_vec.push(AttrExpandItem::Attr(attr));
+                            let _ = reports.push(ClusterReport {
+                                path: cluster_path,
+                                changed: changed_vec,
+                                _node: core::marker::PhantomData,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        for (cluster_path, current_ver) in observed {
+            sub.set_retained_ver(cluster_path, current_ver);
+        }
+
+        // Heartbeat: even with nothing changed, an empty report is sent once
+        // `max_interval` has elapsed so the controller can tell the
+        // subscription is still alive. A report with one or more changed
+        // clusters already proves liveness on its own.
+        if !reports.is_empty() || sub.overdue_for_heartbeat(now) {
+            sub.last_report = now;
+        }
+
+        Ok(reports)
+    }
+}
+
+impl Default for SubscriptionMgr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -21,10 +21,9 @@ use core::pin::pin;
 
 use embassy_futures::select::{select, select_slice, Either};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
-use embassy_time::{Duration, Timer};
-
-use log::{error, info, warn};
+use embassy_time::{Duration, Instant, Timer};
 
+use crate::fmt::{error, info, warn};
 use crate::utils::select::Notification;
 use crate::CommissioningData;
 use crate::{
@@ -42,10 +41,12 @@ use crate::{
 };
 
 use super::{
+    driver::{NetworkDriver, UdpDriver},
     exchange::{
         Exchange, ExchangeCtr, ExchangeCtx, ExchangeId, ExchangeState, Role, MAX_EXCHANGES,
     },
     mrp::ReliableMessage,
+    network::Address,
     packet::{MAX_RX_BUF_SIZE, MAX_RX_STATUS_BUF_SIZE, MAX_TX_BUF_SIZE},
     pipe::{Chunk, Pipe},
 };
@@ -119,6 +120,37 @@ impl PacketBuffers {
     }
 }
 
+/// One dedicated construction-handshake [`Notification`] per exchange slot
+/// (see `handle_rx`'s `complete_construction` completers for why a single
+/// shared one isn't safe once more than one construction can be in flight
+/// at a time). Indexed by a new exchange's position in
+/// `Matter::exchanges`, which - like `PacketBuffers`'s per-`handler_id`
+/// arrays - is stable and unique for as long as that exchange occupies the
+/// slot.
+pub struct ConstructionSlots {
+    notifications: [Notification; MAX_EXCHANGES],
+}
+
+impl ConstructionSlots {
+    fn new() -> Self {
+        Self {
+            notifications: core::array::from_fn(|_| Notification::new()),
+        }
+    }
+}
+
+/// One freshly received exchange's first packet, handed from
+/// `Matter::handle_rx_multiplex` to a `Matter::complete_construction`
+/// completer so the latter can run `Matter::wait_construction` without the
+/// former having to wait around for it.
+pub struct PendingConstruction {
+    slot: usize,
+    len: usize,
+    addr: Address,
+    exchange_id: ExchangeId,
+    buf: [u8; MAX_RX_BUF_SIZE],
+}
+
 impl<'a> Matter<'a> {
     #[cfg(any(feature = "std", feature = "embassy-net"))]
     pub async fn run<D, H>(
@@ -132,6 +164,18 @@ impl<'a> Matter<'a> {
         D: crate::transport::network::NetworkStackDriver,
         H: DataModelHandler,
     {
+        // Borrow `udp_bufs` on its own rather than handing `run_driver` the
+        // whole of `buffers`: `udp` keeps that borrow alive for as long as
+        // the driver runs, and `run_driver` taking `&mut RunBuffers` would
+        // conflict with it even though the two halves of `buffers` never
+        // actually alias.
+        let RunBuffers {
+            udp_bufs,
+            run_bufs,
+            tx_buf,
+            rx_buf,
+        } = buffers;
+
         let udp = crate::transport::udp::UdpListener::new(
             stack,
             crate::transport::network::SocketAddr::new(
@@ -140,17 +184,63 @@ impl<'a> Matter<'a> {
                 ),
                 self.port,
             ),
-            &mut buffers.udp_bufs,
+            udp_bufs,
         )
         .await?;
 
-        let tx_pipe = Pipe::new(unsafe { buffers.tx_buf.assume_init_mut() });
-        let rx_pipe = Pipe::new(unsafe { buffers.rx_buf.assume_init_mut() });
+        self.run_driver_with(&UdpDriver(&udp), run_bufs, tx_buf, rx_buf, dev_comm, handler)
+            .await
+    }
+
+    /// Like [`Self::run`], but driven by an arbitrary [`NetworkDriver`]
+    /// instead of being hard-wired to UDP. This is the entry point for
+    /// transports such as TCP, a Thread/6LoWPAN L2, or a BLE BTP link -
+    /// anything that can move datagrams in and out via `recv`/`send`.
+    /// Combine several transports with [`super::driver::EitherDriver`].
+    pub async fn run_driver<D, H>(
+        &self,
+        driver: &D,
+        buffers: &mut RunBuffers,
+        dev_comm: CommissioningData,
+        handler: &H,
+    ) -> Result<(), Error>
+    where
+        D: NetworkDriver,
+        H: DataModelHandler,
+    {
+        self.run_driver_with(
+            driver,
+            &mut buffers.run_bufs,
+            &mut buffers.tx_buf,
+            &mut buffers.rx_buf,
+            dev_comm,
+            handler,
+        )
+        .await
+    }
+
+    /// The shared implementation behind [`Self::run`] and [`Self::run_driver`]:
+    /// takes the three buffer fields `run_driver` actually touches, rather
+    /// than all of `RunBuffers`, so `run` can keep its `udp_bufs` borrow
+    /// alive across the call (see the comment in `run`).
+    async fn run_driver_with<D, H>(
+        &self,
+        driver: &D,
+        run_bufs: &mut PacketBuffers,
+        tx_buf: &mut TxBuf,
+        rx_buf: &mut RxBuf,
+        dev_comm: CommissioningData,
+        handler: &H,
+    ) -> Result<(), Error>
+    where
+        D: NetworkDriver,
+        H: DataModelHandler,
+    {
+        let tx_pipe = Pipe::new(unsafe { tx_buf.assume_init_mut() });
+        let rx_pipe = Pipe::new(unsafe { rx_buf.assume_init_mut() });
 
         let tx_pipe = &tx_pipe;
         let rx_pipe = &rx_pipe;
-        let udp = &udp;
-        let run_bufs = &mut buffers.run_bufs;
 
         let mut tx = pin!(async move {
             loop {
@@ -158,7 +248,8 @@ impl<'a> Matter<'a> {
                     let mut data = tx_pipe.data.lock().await;
 
                     if let Some(chunk) = data.chunk {
-                        udp.send(chunk.addr.unwrap_udp(), &data.buf[chunk.start..chunk.end])
+                        driver
+                            .send(chunk.addr, &data.buf[chunk.start..chunk.end])
                             .await?;
                         data.chunk = None;
                         tx_pipe.data_consumed_notification.signal(());
@@ -175,12 +266,12 @@ impl<'a> Matter<'a> {
                     let mut data = rx_pipe.data.lock().await;
 
                     if data.chunk.is_none() {
-                        let (len, addr) = udp.recv(data.buf).await?;
+                        let (len, addr) = driver.recv(data.buf).await?;
 
                         data.chunk = Some(Chunk {
                             start: 0,
                             end: len,
-                            addr: crate::transport::network::Address::Udp(addr),
+                            addr,
                         });
                         rx_pipe.data_supplied_notification.signal(());
                     }
@@ -219,9 +310,7 @@ impl<'a> Matter<'a> {
             info!("Comissioning started");
         }
 
-        let construction_notification = Notification::new();
-
-        let mut rx = pin!(self.handle_rx(buffers, rx_pipe, &construction_notification, handler));
+        let mut rx = pin!(self.handle_rx(buffers, rx_pipe, handler));
         let mut tx = pin!(self.handle_tx(tx_pipe));
 
         select(&mut rx, &mut tx).await.unwrap()
@@ -232,15 +321,19 @@ impl<'a> Matter<'a> {
         &self,
         buffers: &mut PacketBuffers,
         rx_pipe: &Pipe<'_>,
-        construction_notification: &Notification,
         handler: &H,
     ) -> Result<(), Error>
     where
         H: DataModelHandler,
     {
-        info!("Creating queue for {} exchanges", 1);
+        info!("Creating queue for {} exchanges", MAX_EXCHANGES);
 
-        let channel = Channel::<NoopRawMutex, _, 1>::new();
+        // A depth-1 channel here would force every newly received exchange
+        // to wait for an idle handler to drain the previous one before the
+        // RX loop can dispatch - and hence read - the next packet. Sizing
+        // the queue to `MAX_EXCHANGES` lets construction fan out to whichever
+        // handler is free without that head-of-line blocking.
+        let channel = Channel::<NoopRawMutex, _, MAX_EXCHANGES>::new();
 
         info!("Creating {} handlers", MAX_EXCHANGES);
         let mut handlers = heapless::Vec::<_, MAX_EXCHANGES>::new();
@@ -266,13 +359,45 @@ impl<'a> Matter<'a> {
                 .unwrap();
         }
 
-        let mut rx = pin!(self.handle_rx_multiplex(rx_pipe, construction_notification, &channel));
+        // A pool of construction-completers, backed by `construction_slots`'
+        // one dedicated `Notification` per exchange slot (see
+        // `ConstructionSlots`). `handle_rx_multiplex` used to run
+        // `wait_construction` itself,
+        // inline, between reading one packet and the next - so a handler
+        // that hadn't yet been scheduled to call `ExchangeCtr::get` stalled
+        // not just its own exchange's construction but every *other*
+        // incoming packet too, new exchange or not. Racing these completers
+        // via `select_slice` alongside the RX loop, the same way `handlers`
+        // above is raced against it, lets `handle_rx_multiplex` hand a
+        // freshly accepted exchange off and immediately go back to reading
+        // the next packet instead of waiting for that handoff to finish.
+        let construction_slots = ConstructionSlots::new();
+        let construction_channel = Channel::<NoopRawMutex, PendingConstruction, MAX_EXCHANGES>::new();
+
+        let mut completers = heapless::Vec::<_, MAX_EXCHANGES>::new();
+        for _ in 0..MAX_EXCHANGES {
+            completers
+                .push(self.complete_construction(&construction_slots, &construction_channel))
+                .map_err(|_| ())
+                .unwrap();
+        }
 
-        let result = select(&mut rx, select_slice(&mut handlers)).await;
+        let mut rx = pin!(self.handle_rx_multiplex(
+            rx_pipe,
+            &construction_slots,
+            &construction_channel,
+            &channel
+        ));
+
+        let result = select(
+            &mut rx,
+            select(select_slice(&mut handlers), select_slice(&mut completers)),
+        )
+        .await;
 
         if let Either::First(result) = result {
             if let Err(e) = &result {
-                error!("Exitting RX loop due to an error: {:?}", e);
+                error!("Exitting RX loop due to an error: {:?}", crate::fmt::dbg(e));
             }
 
             result?;
@@ -315,41 +440,78 @@ impl<'a> Matter<'a> {
     pub async fn handle_rx_multiplex<'t, 'e, const N: usize>(
         &'t self,
         rx_pipe: &Pipe<'_>,
-        construction_notification: &'e Notification,
+        construction_slots: &'e ConstructionSlots,
+        construction_channel: &Channel<NoopRawMutex, PendingConstruction, MAX_EXCHANGES>,
         channel: &Channel<NoopRawMutex, ExchangeCtr<'e>, N>,
     ) -> Result<(), Error>
     where
         't: 'e,
     {
+        // Reused every iteration just to parse the packet far enough to
+        // learn whether it's a new exchange; a new exchange's bytes get
+        // copied again into its own `PendingConstruction` (below) before
+        // this buffer is reused for the next packet, so that copy can't
+        // race this one.
+        let mut own_buf = [0u8; MAX_RX_BUF_SIZE];
+
         loop {
             info!("Transport: waiting for incoming packets");
 
-            {
+            let received = {
                 let mut data = rx_pipe.data.lock().await;
 
-                if let Some(chunk) = data.chunk {
-                    let mut rx = alloc!(Packet::new_rx(&mut data.buf[chunk.start..chunk.end]));
-                    rx.peer = chunk.addr;
-
-                    if let Some(exchange_ctr) =
-                        self.process_rx(construction_notification, &mut rx)?
-                    {
-                        let exchange_id = exchange_ctr.id().clone();
-
-                        info!("Transport: got new exchange: {:?}", exchange_id);
-
-                        channel.send(exchange_ctr).await;
-                        info!("Transport: exchange sent");
-
-                        self.wait_construction(construction_notification, &rx, &exchange_id)
-                            .await?;
-
-                        info!("Transport: exchange started");
-                    }
+                let received = data.chunk.map(|chunk| {
+                    let len = chunk.end - chunk.start;
+                    own_buf[..len].copy_from_slice(&data.buf[chunk.start..chunk.end]);
+                    (len, chunk.addr)
+                });
 
+                if received.is_some() {
                     data.chunk = None;
                     rx_pipe.data_consumed_notification.signal(());
                 }
+
+                received
+            };
+
+            if let Some((len, addr)) = received {
+                let mut rx = alloc!(Packet::new_rx(&mut own_buf[..len]));
+                rx.peer = addr;
+
+                if let Some((exchange_ctr, slot)) = self.process_rx(construction_slots, &mut rx)? {
+                    let exchange_id = exchange_ctr.id().clone();
+
+                    info!(
+                        "Transport: got new exchange: {:?}",
+                        crate::fmt::dbg(&exchange_id)
+                    );
+
+                    channel.send(exchange_ctr).await;
+                    info!("Transport: exchange sent");
+
+                    // Hand the rest of the handshake off to a
+                    // `complete_construction` completer instead of awaiting
+                    // it here: `wait_construction` can take an unbounded
+                    // time waiting for this exchange's handler to actually
+                    // get scheduled and call `ExchangeCtr::get`, and this
+                    // loop used to await that inline, which stalled every
+                    // *other* incoming packet - new exchange or not -
+                    // behind it too.
+                    let mut buf = [0u8; MAX_RX_BUF_SIZE];
+                    buf[..len].copy_from_slice(&own_buf[..len]);
+
+                    construction_channel
+                        .send(PendingConstruction {
+                            slot,
+                            len,
+                            addr,
+                            exchange_id,
+                            buf,
+                        })
+                        .await;
+
+                    info!("Transport: handed off for construction");
+                }
             }
 
             rx_pipe.data_supplied_notification.wait().await
@@ -359,6 +521,40 @@ impl<'a> Matter<'a> {
         Ok::<_, Error>(())
     }
 
+    /// Drains `construction_channel`, finishing the construction handshake
+    /// ([`Self::wait_construction`]) for whichever new exchange is next -
+    /// see the comment in `handle_rx_multiplex` for why this runs as its
+    /// own pool of completers rather than inline in that loop. Like
+    /// [`Self::exchange_handler`], any number of these can run
+    /// concurrently, each pulling the next pending handshake off the same
+    /// channel as soon as it's free.
+    #[inline(always)]
+    async fn complete_construction<'t, 'e>(
+        &'t self,
+        construction_slots: &'e ConstructionSlots,
+        construction_channel: &Channel<NoopRawMutex, PendingConstruction, MAX_EXCHANGES>,
+    ) -> Result<(), Error>
+    where
+        't: 'e,
+    {
+        loop {
+            let mut pending = construction_channel.recv().await;
+
+            let mut rx = alloc!(Packet::new_rx(&mut pending.buf[..pending.len]));
+            rx.peer = pending.addr;
+
+            self.wait_construction(
+                &construction_slots.notifications[pending.slot],
+                &rx,
+                &pending.exchange_id,
+            )
+            .await?;
+        }
+
+        #[allow(unreachable_code)]
+        Ok::<_, Error>(())
+    }
+
     #[inline(always)]
     pub async fn exchange_handler<const N: usize, H>(
         &self,
@@ -378,7 +574,7 @@ impl<'a> Matter<'a> {
             info!(
                 "Handler {}: Got exchange {:?}",
                 handler_id,
-                exchange_ctr.id()
+                crate::fmt::dbg(exchange_ctr.id())
             );
 
             let result = self
@@ -388,7 +584,8 @@ impl<'a> Matter<'a> {
             if let Err(err) = result {
                 warn!(
                     "Handler {}: Exchange closed because of error: {:?}",
-                    handler_id, err
+                    handler_id,
+                    crate::fmt::dbg(&err)
                 );
             } else {
                 info!("Handler {}: Exchange completed", handler_id);
@@ -447,12 +644,18 @@ impl<'a> Matter<'a> {
 
     pub fn process_rx<'r>(
         &'r self,
-        construction_notification: &'r Notification,
+        construction_slots: &'r ConstructionSlots,
         src_rx: &mut Packet<'_>,
-    ) -> Result<Option<ExchangeCtr<'r>>, Error> {
+    ) -> Result<Option<(ExchangeCtr<'r>, usize)>, Error> {
         self.purge()?;
 
         let mut exchanges = self.exchanges.borrow_mut();
+        // Captured before `post_recv` takes a mutable borrow of `exchanges`
+        // that `ctx` then holds for the rest of this function - a raw
+        // pointer doesn't keep that borrow contested the way calling
+        // `exchanges.as_ptr()` again later would.
+        let exchanges_ptr: *const ExchangeCtx = exchanges.as_ptr();
+
         let (ctx, new) = match self.post_recv(&mut exchanges, src_rx) {
             Ok((ctx, new)) => (ctx, new),
             Err(e) => match e.code() {
@@ -477,9 +680,11 @@ impl<'a> Matter<'a> {
                         tx_acknowledged, ..
                     } => {
                         *tx_acknowledged = true;
+                        ctx.mrp.ack_received();
                     }
                     ExchangeState::CompleteAcknowledge { notification, .. } => {
                         unsafe { notification.as_ref() }.unwrap().signal(());
+                        ctx.mrp.ack_received();
                         ctx.state = ExchangeState::Closed;
                     }
                     _ => {
@@ -493,18 +698,26 @@ impl<'a> Matter<'a> {
         }
 
         if new {
+            // SAFETY: `exchanges` is a fixed-capacity `heapless::Vec` that
+            // never reallocates, and `ctx` borrows directly into its
+            // backing storage, so this offset is a stable index into
+            // `construction_slots` for as long as this exchange occupies
+            // this slot - i.e. until `wait_construction` moves it out of
+            // `ExchangeState::Construction`.
+            let slot = unsafe { (ctx as *const ExchangeCtx).offset_from(exchanges_ptr) } as usize;
+
             let constructor = ExchangeCtr {
                 exchange: Exchange {
                     id: ctx.id.clone(),
                     matter: self,
                     notification: Notification::new(),
                 },
-                construction_notification,
+                construction_notification: &construction_slots.notifications[slot],
             };
 
             self.notify_changed();
 
-            Ok(Some(constructor))
+            Ok(Some((constructor, slot)))
         } else if src_rx.proto.proto_id == PROTO_ID_SECURE_CHANNEL
             && src_rx.proto.proto_opcode == OpCode::MRPStandAloneAck as u8
         {
@@ -564,11 +777,20 @@ impl<'a> Matter<'a> {
     }
 
     pub async fn wait_tx(&self) -> Result<(), Error> {
-        select(
-            self.send_notification.wait(),
-            Timer::after(Duration::from_millis(100)),
-        )
-        .await;
+        let next_deadline = {
+            let exchanges = self.exchanges.borrow();
+
+            exchanges
+                .iter()
+                .filter_map(|ctx| ctx.mrp.next_retrans_deadline())
+                .min()
+        };
+
+        let timeout = next_deadline.map_or(Duration::from_millis(100), |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+
+        select(self.send_notification.wait(), Timer::after(timeout)).await;
 
         Ok(())
     }
@@ -578,22 +800,31 @@ impl<'a> Matter<'a> {
 
         let mut exchanges = self.exchanges.borrow_mut();
 
-        let ctx = exchanges.iter_mut().find(|ctx| {
-            matches!(
-                &ctx.state,
-                ExchangeState::Acknowledge { .. }
-                    | ExchangeState::ExchangeSend { .. }
-                    // | ExchangeState::ExchangeRecv {
-                    //     tx_acknowledged: false,
-                    //     ..
-                    // }
-                    | ExchangeState::Complete { .. } // | ExchangeState::CompleteAcknowledge { .. }
-            ) || ctx.mrp.is_ack_ready(*self.borrow())
+        let now = Instant::now();
+
+        let ctx = exchanges.iter_mut().find(|ctx| match &ctx.state {
+            ExchangeState::Acknowledge { .. }
+            | ExchangeState::ExchangeSend { .. }
+            | ExchangeState::Complete { .. } => true,
+            ExchangeState::ExchangeRecv {
+                tx_acknowledged: false,
+                ..
+            }
+            | ExchangeState::CompleteAcknowledge { .. } => ctx.mrp.is_retrans_due(now),
+            _ => ctx.mrp.is_ack_ready(*self.borrow()),
         });
 
         if let Some(ctx) = ctx {
             self.notify_changed();
 
+            let is_retransmission = matches!(
+                ctx.state,
+                ExchangeState::ExchangeRecv {
+                    tx_acknowledged: false,
+                    ..
+                } | ExchangeState::CompleteAcknowledge { .. }
+            );
+            let retransmit = is_retransmission.then(|| ctx.mrp.retransmit());
             let state = &mut ctx.state;
 
             let send = match state {
@@ -622,10 +853,23 @@ impl<'a> Matter<'a> {
 
                     true
                 }
-                // ExchangeState::ExchangeRecv { .. } => {
-                //     // TODO: Re-send the tx package if due
-                //     false
-                // }
+                ExchangeState::ExchangeRecv {
+                    _tx,
+                    tx_acknowledged: false,
+                    ..
+                } => {
+                    // Re-send the previously transmitted packet;
+                    // `retransmit()` above already advanced the backoff
+                    // schedule for this send.
+                    if matches!(retransmit, Some(Err(_))) {
+                        *state = ExchangeState::Closed;
+                        false
+                    } else {
+                        dest_tx.load(_tx)?;
+
+                        true
+                    }
+                }
                 ExchangeState::Complete { tx, notification } => {
                     let tx = unsafe { tx.as_ref() }.unwrap();
                     dest_tx.load(tx)?;
@@ -637,10 +881,18 @@ impl<'a> Matter<'a> {
 
                     true
                 }
-                // ExchangeState::CompleteAcknowledge { .. } => {
-                //     // TODO: Re-send the tx package if due
-                //     false
-                // }
+                ExchangeState::CompleteAcknowledge { _tx, .. } => {
+                    // Re-send the final packet until its ack arrives.
+                    if matches!(retransmit, Some(Err(_))) {
+                        *state = ExchangeState::Closed;
+                        false
+                    } else {
+                        let tx = unsafe { _tx.as_ref() }.unwrap();
+                        dest_tx.load(tx)?;
+
+                        true
+                    }
+                }
                 _ => {
                     ReliableMessage::prepare_ack(ctx.id.id, dest_tx);
                     true
@@ -728,7 +980,14 @@ impl<'a> Matter<'a> {
         }
 
         session.pre_send(tx)?;
-        ctx.mrp.pre_send(tx)?;
+
+        // TCP already guarantees reliable, in-order delivery, so exchanges
+        // carried over it must not also be made reliable at the MRP layer -
+        // doing so would just add redundant acks and retransmissions.
+        if !matches!(ctx.id.session_id.peer_addr, crate::transport::network::Address::Tcp(_)) {
+            ctx.mrp.pre_send(tx)?;
+        }
+
         session_mgr.send(sess_index, tx)
     }
 
@@ -751,7 +1010,7 @@ impl<'a> Matter<'a> {
                 Err(ErrorCode::NoExchange.into())
             }
         } else if create_new {
-            info!("Creating new exchange: {:?}", id);
+            info!("Creating new exchange: {:?}", crate::fmt::dbg(&id));
 
             let exchange = ExchangeCtx {
                 id,
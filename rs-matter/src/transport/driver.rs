@@ -0,0 +1,122 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A channel-driven transport abstraction for the Matter transport core.
+//!
+//! `run` hard-wires `run_piped` to a single UDP socket. `NetworkDriver` lets
+//! any packet source/sink - TCP, a Thread/6LoWPAN L2, a BLE BTP transport -
+//! feed the same exchange machinery by only implementing `recv`/`send`,
+//! mirroring the split embassy-net-driver-channel uses to keep its protocol
+//! loop backend-agnostic.
+
+use crate::error::Error;
+use crate::transport::network::Address;
+
+/// A packet source/sink that can be multiplexed into the Matter exchange
+/// machinery by [`crate::Matter::run_driver`].
+///
+/// Implementors only need to move raw datagrams in and out of the transport;
+/// framing, retransmission and exchange dispatch all happen above this trait.
+pub trait NetworkDriver {
+    /// Receive a single datagram into `buf`, returning its length and the
+    /// address it was received from.
+    async fn recv(&self, buf: &mut [u8]) -> Result<(usize, Address), Error>;
+
+    /// Send `buf` as a single datagram to `addr`.
+    async fn send(&self, addr: Address, buf: &[u8]) -> Result<(), Error>;
+
+    /// Whether this driver is the right one to carry traffic for `addr`.
+    ///
+    /// Used by [`EitherDriver`] to route an outbound packet to the transport
+    /// that owns its peer's address space. The default accepts everything,
+    /// which is correct for a single, standalone driver.
+    fn owns(&self, _addr: &Address) -> bool {
+        true
+    }
+}
+
+/// Combines two [`NetworkDriver`]s - e.g. UDP and a BLE BTP transport - into
+/// one, so `run_driver` keeps dealing with a single driver while traffic is
+/// actually carried over whichever of the two a peer's [`Address`] belongs
+/// to. Nest `EitherDriver`s to combine more than two transports.
+pub struct EitherDriver<A, B>(pub A, pub B);
+
+impl<A, B> NetworkDriver for EitherDriver<A, B>
+where
+    A: NetworkDriver,
+    B: NetworkDriver,
+{
+    async fn recv(&self, buf: &mut [u8]) -> Result<(usize, Address), Error> {
+        // `buf` can't be lent to both sub-recv futures at once, so each side
+        // races into its own scratch buffer and the winner is copied out.
+        let mut buf_a = [0u8; crate::transport::packet::MAX_RX_BUF_SIZE];
+        let mut buf_b = [0u8; crate::transport::packet::MAX_RX_BUF_SIZE];
+
+        let (len, addr, src) = match embassy_futures::select::select(
+            self.0.recv(&mut buf_a),
+            self.1.recv(&mut buf_b),
+        )
+        .await
+        {
+            embassy_futures::select::Either::First(result) => {
+                let (len, addr) = result?;
+                (len, addr, &buf_a[..len])
+            }
+            embassy_futures::select::Either::Second(result) => {
+                let (len, addr) = result?;
+                (len, addr, &buf_b[..len])
+            }
+        };
+
+        buf[..len].copy_from_slice(src);
+
+        Ok((len, addr))
+    }
+
+    async fn send(&self, addr: Address, buf: &[u8]) -> Result<(), Error> {
+        if self.0.owns(&addr) {
+            self.0.send(addr, buf).await
+        } else {
+            self.1.send(addr, buf).await
+        }
+    }
+
+    fn owns(&self, addr: &Address) -> bool {
+        self.0.owns(addr) || self.1.owns(addr)
+    }
+}
+
+/// Adapts a [`crate::transport::udp::UdpListener`] to [`NetworkDriver`] so the
+/// existing UDP path can keep using `run` unchanged while also being usable
+/// through the generic, multi-transport `run_driver`.
+pub struct UdpDriver<'a>(pub &'a crate::transport::udp::UdpListener<'a>);
+
+impl<'a> NetworkDriver for UdpDriver<'a> {
+    async fn recv(&self, buf: &mut [u8]) -> Result<(usize, Address), Error> {
+        let (len, addr) = self.0.recv(buf).await?;
+
+        Ok((len, Address::Udp(addr)))
+    }
+
+    async fn send(&self, addr: Address, buf: &[u8]) -> Result<(), Error> {
+        self.0.send(addr.unwrap_udp(), buf).await
+    }
+
+    fn owns(&self, addr: &Address) -> bool {
+        matches!(addr, Address::Udp(_))
+    }
+}
@@ -0,0 +1,231 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use embassy_time::{Duration, Instant};
+
+use crate::error::{Error, ErrorCode};
+use crate::secure_channel::common::OpCode;
+use crate::transport::packet::Packet;
+
+/// Default retransmit interval while the peer's session is active, per the
+/// Matter MRP schedule (§4.12.8.1, `MRP_BACKOFF_BASE` family of constants).
+const MRP_ACTIVE_RETRANS_TIMEOUT_MS: u64 = 300;
+/// Default retransmit interval once the peer's session is considered idle.
+const MRP_IDLE_RETRANS_TIMEOUT_MS: u64 = 500;
+
+const MRP_BACKOFF_BASE: f32 = 1.6;
+const MRP_BACKOFF_MARGIN: f32 = 1.1;
+const MRP_BACKOFF_THRESHOLD: u8 = 1;
+const MRP_BACKOFF_JITTER: f32 = 0.25;
+
+/// Total number of times a reliable message may be sent (the original send
+/// plus at most `MRP_MAX_TRANSMISSIONS - 1` retries) before the exchange is
+/// abandoned.
+const MRP_MAX_TRANSMISSIONS: u8 = 5;
+
+/// How long a session may go without activity before its retransmissions
+/// fall back to the slower idle schedule (Matter's `SESSION_ACTIVE_THRESHOLD`).
+const SESSION_ACTIVE_THRESHOLD_MS: u64 = 4000;
+
+/// Per-exchange Message Reliability Protocol state: ack bookkeeping plus,
+/// while a reliable message is outstanding, its retransmission schedule.
+///
+/// Whether the peer counts as "active" for backoff purposes is tracked here,
+/// from what this exchange receives from the peer, rather than threaded in
+/// by the caller -
+/// callers have no cheaper way to know the session's last-activity time than
+/// asking the `ReliableMessage`s that observe it.
+#[derive(Debug)]
+pub struct ReliableMessage {
+    ack_state: AckState,
+    retrans: Option<RetransState>,
+    last_activity: Instant,
+}
+
+impl Default for ReliableMessage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+enum AckState {
+    #[default]
+    Idle,
+    AckSendDue {
+        msg_ctr: u32,
+    },
+}
+
+#[derive(Debug)]
+struct RetransState {
+    sent_at: Instant,
+    /// 0-based attempt counter: 0 is the original send, N is the Nth retry.
+    attempt: u8,
+}
+
+impl ReliableMessage {
+    pub fn new() -> Self {
+        Self {
+            ack_state: AckState::Idle,
+            retrans: None,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Whether the peer has been heard from recently enough to use the
+    /// faster "active" retransmission schedule.
+    fn is_active(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_activity)
+            < Duration::from_millis(SESSION_ACTIVE_THRESHOLD_MS)
+    }
+
+    /// Process an incoming packet: track whether it requires a standalone
+    /// ack and, if it itself acknowledges a message, let the caller clear
+    /// retransmission state.
+    pub fn recv(&mut self, rx: &Packet, _epoch: impl Fn() -> Instant) -> Result<(), Error> {
+        self.last_activity = Instant::now();
+
+        if rx.proto.is_reliable() {
+            self.ack_state = AckState::AckSendDue {
+                msg_ctr: rx.proto.ctr,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Whether a standalone ack is due to be sent for this exchange.
+    pub fn is_ack_ready(&self, _epoch: impl Fn() -> Instant) -> bool {
+        matches!(self.ack_state, AckState::AckSendDue { .. })
+    }
+
+    pub fn prepare_ack(exch_id: u16, tx: &mut Packet) {
+        tx.proto.proto_id = crate::secure_channel::common::PROTO_ID_SECURE_CHANNEL;
+        tx.proto.proto_opcode = OpCode::MRPStandAloneAck as u8;
+        tx.proto.exch_id = exch_id;
+    }
+
+    /// Mark the outgoing packet reliable (unless it is itself a standalone
+    /// ack, which must never be made reliable - acking an ack would cause an
+    /// ack storm) and, for reliable sends, arm the retransmission timer.
+    pub fn pre_send(&mut self, tx: &mut Packet) -> Result<(), Error> {
+        if let AckState::AckSendDue { msg_ctr } = self.ack_state {
+            tx.proto.set_ack(msg_ctr);
+            self.ack_state = AckState::Idle;
+        }
+
+        let is_standalone_ack =
+            tx.proto.proto_id == crate::secure_channel::common::PROTO_ID_SECURE_CHANNEL
+                && tx.proto.proto_opcode == OpCode::MRPStandAloneAck as u8;
+
+        if !is_standalone_ack {
+            tx.proto.set_reliable();
+
+            // A retransmission already advanced `attempt` (and `sent_at`)
+            // via `retransmit()` just before this call, for this same send;
+            // only arm a fresh `attempt: 0` schedule here for a message's
+            // first send; `pre_send` runs on every send; re-arming on a
+            // retransmission too would wipe out the backoff progress
+            // `retransmit()` just recorded, and the exchange would retry
+            // forever at the base interval instead of backing off and
+            // eventually timing out.
+            if self.retrans.is_none() {
+                self.retrans = Some(RetransState {
+                    sent_at: Instant::now(),
+                    attempt: 0,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// An ack for the outstanding reliable message arrived: stop retrying.
+    pub fn ack_received(&mut self) {
+        self.last_activity = Instant::now();
+        self.retrans = None;
+    }
+
+    /// Whether the outstanding reliable message's retry deadline has passed.
+    pub fn is_retrans_due(&self, now: Instant) -> bool {
+        self.retrans.as_ref().is_some_and(|r| {
+            now >= r.sent_at + retrans_timeout(r.attempt, self.is_active(now), self.salt())
+        })
+    }
+
+    /// The nearest instant at which this exchange needs `pull_tx` to be
+    /// polled again for a retransmission, if any is outstanding.
+    pub fn next_retrans_deadline(&self) -> Option<Instant> {
+        let now = Instant::now();
+
+        self.retrans
+            .as_ref()
+            .map(|r| r.sent_at + retrans_timeout(r.attempt, self.is_active(now), self.salt()))
+    }
+
+    /// A value that decorrelates this exchange's jitter from every other
+    /// exchange's, without pulling in a full RNG: exchanges are distinct
+    /// allocations, so their addresses already differ.
+    fn salt(&self) -> u32 {
+        self as *const Self as u32
+    }
+
+    /// Record that the outstanding message is being retransmitted now.
+    /// Returns a timeout error once `MRP_MAX_TRANSMISSIONS` has been reached.
+    pub fn retransmit(&mut self) -> Result<(), Error> {
+        let retrans = self.retrans.as_mut().ok_or(ErrorCode::NoExchange)?;
+
+        retrans.attempt += 1;
+        retrans.sent_at = Instant::now();
+
+        if retrans.attempt >= MRP_MAX_TRANSMISSIONS {
+            self.retrans = None;
+            Err(ErrorCode::Timeout)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn retrans_timeout(attempt: u8, active: bool, salt: u32) -> Duration {
+    let base_ms = if active {
+        MRP_ACTIVE_RETRANS_TIMEOUT_MS
+    } else {
+        MRP_IDLE_RETRANS_TIMEOUT_MS
+    };
+
+    if attempt == 0 {
+        return Duration::from_millis(base_ms);
+    }
+
+    let jitter = 1.0 + pseudo_rand_unit(salt) * MRP_BACKOFF_JITTER;
+    let backoff_exp = (attempt.saturating_sub(MRP_BACKOFF_THRESHOLD)) as i32;
+    let factor = MRP_BACKOFF_MARGIN * MRP_BACKOFF_BASE.powi(backoff_exp) * jitter;
+
+    Duration::from_millis((base_ms as f32 * factor) as u64)
+}
+
+/// A cheap, deterministic-enough source of jitter: no full RNG is pulled in
+/// for a no_std transport core, just enough spread to avoid synchronized
+/// retries. `salt` distinguishes otherwise-identical retries polled in the
+/// same millisecond tick (e.g. several exchanges retransmitting together) -
+/// `Instant::now()` alone would give them all the same jitter.
+fn pseudo_rand_unit(salt: u32) -> f32 {
+    let ticks = Instant::now().as_millis() as u32;
+    (ticks.wrapping_add(salt.wrapping_mul(2_654_435_761)) % 1000) as f32 / 1000.0
+}
@@ -0,0 +1,218 @@
+/*
+ *
+ *    Copyright (c) 2020-2022 Project CHIP Authors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Matter-over-TCP transport.
+//!
+//! UDP can only carry a single datagram per message, so large interaction
+//! model payloads (big reads, subscriptions reports) cannot be carried over
+//! it. This module adapts a pool of accepted TCP connections to the same
+//! [`super::driver::NetworkDriver`] the UDP path uses, applying the Matter
+//! TCP framing: every message is preceded by a 4-byte big-endian length.
+//!
+//! `recv` has to stay available to accept a brand-new connection *and* read
+//! the next frame off every connection already open - a new controller
+//! showing up must not be starved by one that's gone quiet, nor vice versa -
+//! so it races `listener.accept()` against a read of each tracked connection
+//! with [`select`]/[`select_slice`], the same pattern `Matter::handle_rx`
+//! uses to fan its own handler pool out.
+//!
+//! TCP already guarantees in-order, reliable delivery, so exchanges carried
+//! over it must not also be made reliable at the MRP layer - see
+//! `Matter::pre_send`, which skips `ReliableMessage::pre_send` whenever the
+//! destination peer address is [`Address::Tcp`].
+
+use core::cell::UnsafeCell;
+
+use embassy_futures::select::{select, select_slice, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+
+use crate::error::{Error, ErrorCode};
+use crate::transport::driver::NetworkDriver;
+use crate::transport::network::{Address, TcpConnection, TcpListener, TcpSocketAddr};
+use crate::transport::packet::MAX_RX_BUF_SIZE;
+
+/// Maximum number of concurrently open inbound TCP connections this driver
+/// will track; one per simultaneously connected controller.
+const MAX_TCP_CONNECTIONS: usize = 4;
+
+const LEN_PREFIX_SIZE: usize = 4;
+
+/// Adapts a pool of accepted TCP connections, each framed with a 4-byte
+/// big-endian length prefix, to [`NetworkDriver`]'s single-datagram
+/// `recv`/`send` so it can be multiplexed into the Matter exchange machinery
+/// exactly like the UDP path.
+pub struct TcpDriver<'a> {
+    listener: &'a TcpListener,
+    // Which peer occupies each slot, guarded by its own lock held only long
+    // enough to read or update slot occupancy - never across a connection
+    // read or write - so accepting a new connection, or reading one slot,
+    // never waits on another slot's in-flight I/O.
+    addrs: Mutex<NoopRawMutex, heapless::Vec<TcpSocketAddr, MAX_TCP_CONNECTIONS>>,
+    // One independent lock per slot: a connection that's gone quiet only
+    // holds its own slot's lock while its read is pending, so it can never
+    // starve a read of, or a new accept into, any other slot.
+    conns: [Mutex<NoopRawMutex, Option<TcpConnection>>; MAX_TCP_CONNECTIONS],
+    // Per-slot scratch, indexed the same as `conns`. A frame read races
+    // against reads of every other open connection (see `recv`), so each
+    // slot needs its own buffer rather than sharing the caller's - mirrors
+    // `driver::EitherDriver::recv`'s reasoning for the same problem.
+    //
+    // Safety: at most one `read_frame` future per slot index is ever polled
+    // concurrently - `recv` builds exactly one read future per currently
+    // tracked connection before racing them - so the aliasing this
+    // `UnsafeCell` permits is never exercised.
+    scratch: [UnsafeCell<[u8; MAX_RX_BUF_SIZE]>; MAX_TCP_CONNECTIONS],
+}
+
+// SAFETY: see the invariant documented on the `scratch` field above.
+unsafe impl<'a> Sync for TcpDriver<'a> {}
+
+impl<'a> TcpDriver<'a> {
+    pub fn new(listener: &'a TcpListener) -> Self {
+        Self {
+            listener,
+            addrs: Mutex::new(heapless::Vec::new()),
+            conns: [const { Mutex::new(None) }; MAX_TCP_CONNECTIONS],
+            scratch: [const { UnsafeCell::new([0u8; MAX_RX_BUF_SIZE]) }; MAX_TCP_CONNECTIONS],
+        }
+    }
+
+    /// Accepts one new connection and installs it in the pool, evicting
+    /// whatever connection previously occupied the first slot if the pool
+    /// was already full - Matter controllers reconnect rather than
+    /// multiplex several sockets, so the newest connection always wins a
+    /// slot.
+    async fn accept_into_pool(&self) -> Result<usize, Error> {
+        let conn = self.listener.accept().await?;
+        let addr = conn.peer_addr();
+
+        let idx = {
+            let mut addrs = self.addrs.lock().await;
+
+            if let Some(idx) = addrs.iter().position(|a| *a == addr) {
+                idx
+            } else if addrs.len() == MAX_TCP_CONNECTIONS {
+                addrs[0] = addr;
+                0
+            } else {
+                addrs.push(addr).map_err(|_| ErrorCode::NoSpace)?;
+                addrs.len() - 1
+            }
+        };
+
+        *self.conns[idx].lock().await = Some(conn);
+
+        Ok(idx)
+    }
+
+    /// Reads one length-prefixed frame from the connection in `slot` into
+    /// that slot's scratch buffer.
+    ///
+    /// Holds only `conns[slot]`'s lock across the read - never `addrs`, and
+    /// never another slot's lock - so this can run concurrently with a read
+    /// of any other slot, or with `accept_into_pool` installing a brand-new
+    /// connection into a different slot.
+    async fn read_frame(&self, slot: usize) -> Result<(TcpSocketAddr, usize), Error> {
+        let addr = self.addrs.lock().await[slot];
+
+        let mut conn = self.conns[slot].lock().await;
+        let conn = conn.as_mut().ok_or(ErrorCode::NoSession)?;
+
+        let mut len_buf = [0u8; LEN_PREFIX_SIZE];
+        conn.read_exact(&mut len_buf).await?;
+
+        let len = u32::from_be_bytes(len_buf) as usize;
+        if len > MAX_RX_BUF_SIZE {
+            Err(ErrorCode::NoSpace)?;
+        }
+
+        // SAFETY: see the invariant documented on `scratch`.
+        let scratch = unsafe { &mut *self.scratch[slot].get() };
+        conn.read_exact(&mut scratch[..len]).await?;
+
+        Ok((addr, len))
+    }
+}
+
+impl<'a> NetworkDriver for TcpDriver<'a> {
+    async fn recv(&self, buf: &mut [u8]) -> Result<(usize, Address), Error> {
+        loop {
+            let slot_count = self.addrs.lock().await.len();
+
+            if slot_count == 0 {
+                // Nothing open yet: only a new connection can produce a frame.
+                self.accept_into_pool().await?;
+                continue;
+            }
+
+            let mut reads: heapless::Vec<_, MAX_TCP_CONNECTIONS> = heapless::Vec::new();
+            for slot in 0..slot_count {
+                let _ = reads.push(self.read_frame(slot));
+            }
+
+            match select(self.accept_into_pool(), select_slice(&mut reads)).await {
+                Either::First(_) => {
+                    // A new controller connected; loop back round so the
+                    // next `select` also races a read of it.
+                    continue;
+                }
+                Either::Second((result, idx)) => {
+                    let (addr, len) = result?;
+
+                    // SAFETY: `result` is the slot this read just finished
+                    // with, so its scratch buffer is no longer concurrently
+                    // accessed by any in-flight future.
+                    let scratch = unsafe { &*self.scratch[idx].get() };
+                    buf[..len].copy_from_slice(&scratch[..len]);
+
+                    return Ok((len, Address::Tcp(addr)));
+                }
+            }
+        }
+    }
+
+    async fn send(&self, addr: Address, buf: &[u8]) -> Result<(), Error> {
+        let addr = addr.unwrap_tcp();
+
+        let idx = {
+            let addrs = self.addrs.lock().await;
+            addrs
+                .iter()
+                .position(|a| *a == addr)
+                .ok_or(ErrorCode::NoSession)?
+        };
+
+        let mut conn = self.conns[idx].lock().await;
+        let conn = conn.as_mut().ok_or(ErrorCode::NoSession)?;
+
+        let mut framed: heapless::Vec<u8, { LEN_PREFIX_SIZE + super::packet::MAX_TX_BUF_SIZE }> =
+            heapless::Vec::new();
+        framed
+            .extend_from_slice(&(buf.len() as u32).to_be_bytes())
+            .map_err(|_| ErrorCode::NoSpace)?;
+        framed
+            .extend_from_slice(buf)
+            .map_err(|_| ErrorCode::NoSpace)?;
+
+        conn.write_all(&framed).await
+    }
+
+    fn owns(&self, addr: &Address) -> bool {
+        matches!(addr, Address::Tcp(_))
+    }
+}